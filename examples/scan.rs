@@ -3,7 +3,7 @@ use std::thread;
 use std::time;
 
 fn main() {
-    let mut pmw3901 = pmw3901::Pmw3901::new(0, 0).unwrap();
+    let mut pmw3901 = pmw3901::linux::open(0, 0).unwrap();
     pmw3901.init().unwrap();
 
     loop {
@@ -0,0 +1,25 @@
+//! Tunables for [`crate::Pmw3901`].
+
+use std::time::Duration;
+
+/// Configuration for a [`crate::Pmw3901`], mirroring the `Default`-able
+/// `Config` structs used by embedded-hal peripheral drivers.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Maximum SPI clock speed. The PMW3901 datasheet allows up to ~2 MHz;
+    /// lower this for margin on noisy wiring.
+    pub max_speed_hz: u32,
+    /// Minimum spacing between motion-data polls. The driver only stores
+    /// this value (see [`crate::Pmw3901::config`]); `read_sample` and
+    /// `read_motion_burst` do not wait for or check it.
+    pub poll_interval: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_speed_hz: 2_000_000,
+            poll_interval: Duration::from_millis(10),
+        }
+    }
+}
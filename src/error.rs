@@ -0,0 +1,61 @@
+//! Error type returned by [`crate::Pmw3901`].
+
+use core::fmt;
+
+/// Errors that can occur while talking to the sensor.
+///
+/// Generic over `E`, the underlying SPI bus's error type, so a transient bus
+/// glitch can be told apart from a genuinely bad response and handled
+/// accordingly (e.g. retrying `init()` or skipping a corrupt sample).
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum Pmw3901Error<E> {
+    /// The underlying SPI transaction failed.
+    Spi(E),
+    /// A register read or write got back a byte other than the expected
+    /// `0xff` (the first byte of a read; either byte of a write).
+    UnexpectedResponse {
+        /// Register address that was read.
+        addr: u8,
+        /// Byte actually received.
+        got: u8,
+    },
+    /// `init()` read back a product ID (expected `0x49`) or inverse product
+    /// ID (expected `0xb6`) that doesn't match the PMW3901.
+    BadProductId {
+        /// Byte actually received.
+        got: u8,
+    },
+    /// `write_register` was asked to write to an address that already had
+    /// the write bit (`0x80`) set. `write_registers` does not check this.
+    WriteBitSet,
+}
+
+impl<E: fmt::Debug> fmt::Display for Pmw3901Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pmw3901Error::Spi(e) => write!(f, "SPI transaction failed: {:?}", e),
+            Pmw3901Error::UnexpectedResponse { addr, got } => write!(
+                f,
+                "unexpected byte in response to register 0x{:02x}: 0x{:02x}",
+                addr, got
+            ),
+            Pmw3901Error::BadProductId { got } => {
+                write!(
+                    f,
+                    "unexpected product id or inverse product id: 0x{:02x} (expected 0x49 / 0xb6)",
+                    got
+                )
+            }
+            Pmw3901Error::WriteBitSet => write!(f, "write bit already set on register address"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for Pmw3901Error<E> {}
+
+impl<E> From<E> for Pmw3901Error<E> {
+    fn from(e: E) -> Self {
+        Pmw3901Error::Spi(e)
+    }
+}
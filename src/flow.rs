@@ -0,0 +1,183 @@
+//! Dead-reckoning integrator that turns raw pixel flow into metric
+//! displacement.
+
+use crate::{MotionBurst, Pmw3901Sample};
+
+/// Approximate PMW3901 lens field of view, in radians (~42 degrees).
+pub const DEFAULT_FOV_RAD: f32 = 0.733;
+
+/// Width (in pixels) of the PMW3901's imaging array.
+pub const DEFAULT_RESOLUTION: f32 = 35.0;
+
+/// How the sensor's X/Y axes map onto the vehicle's, so callers don't have
+/// to pre-rotate samples to match however the board is mounted.
+#[derive(Debug, Clone, Copy)]
+pub enum Orientation {
+    /// Sensor X/Y already match the vehicle's forward/right axes.
+    Normal,
+    /// Board mounted rotated 90 degrees: axes swapped, X inverted.
+    Rotate90,
+    /// Board mounted rotated 180 degrees: both axes inverted.
+    Rotate180,
+    /// Board mounted rotated 270 degrees: axes swapped, Y inverted.
+    Rotate270,
+}
+
+impl Orientation {
+    fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        match self {
+            Orientation::Normal => (x, y),
+            Orientation::Rotate90 => (-y, x),
+            Orientation::Rotate180 => (-x, -y),
+            Orientation::Rotate270 => (y, -x),
+        }
+    }
+}
+
+/// Accumulates per-frame pixel flow into an estimated 2-D position.
+///
+/// Converts pixel flow to metric displacement with
+/// `distance = (pixels / resolution) * height * tan(fov / 2)`, using the
+/// sensor's angular resolution/field of view and a per-frame height input,
+/// since the PMW3901 reports optical flow in pixels, not distance.
+pub struct FlowIntegrator {
+    orientation: Orientation,
+    resolution: f32,
+    fov_rad: f32,
+    total_x_m: f32,
+    total_y_m: f32,
+}
+
+impl FlowIntegrator {
+    /// Creates an integrator using the PMW3901's stock lens constants
+    /// ([`DEFAULT_RESOLUTION`], [`DEFAULT_FOV_RAD`]).
+    pub fn new(orientation: Orientation) -> FlowIntegrator {
+        FlowIntegrator::with_optics(orientation, DEFAULT_RESOLUTION, DEFAULT_FOV_RAD)
+    }
+
+    /// Creates an integrator for a non-stock lens/resolution.
+    pub fn with_optics(orientation: Orientation, resolution: f32, fov_rad: f32) -> FlowIntegrator {
+        FlowIntegrator {
+            orientation,
+            resolution,
+            fov_rad,
+            total_x_m: 0.0,
+            total_y_m: 0.0,
+        }
+    }
+
+    /// Folds in one [`Pmw3901::read_sample`](crate::Pmw3901::read_sample)
+    /// reading at the given height above ground, in meters. Returns the
+    /// incremental (dx, dy) displacement, also in meters, that was added to
+    /// the running total.
+    pub fn update(&mut self, sample: &Pmw3901Sample, height_m: f32) -> (f32, f32) {
+        self.fold(sample.x, sample.y, height_m)
+    }
+
+    /// Like [`FlowIntegrator::update`], but sourced from a
+    /// [`MotionBurst`](crate::MotionBurst) read via
+    /// [`Pmw3901::read_motion_burst`](crate::Pmw3901::read_motion_burst).
+    /// This is how the two features compose: check `burst.squal`/
+    /// `burst.shutter` first and skip folding in frames the burst marks as
+    /// poor quality or over/under-exposed, rather than feeding every frame
+    /// in blind.
+    pub fn update_from_burst(&mut self, burst: &MotionBurst, height_m: f32) -> (f32, f32) {
+        self.fold(burst.dx, burst.dy, height_m)
+    }
+
+    fn fold(&mut self, x: i16, y: i16, height_m: f32) -> (f32, f32) {
+        let (x, y) = self.orientation.apply(x as f32, y as f32);
+        let per_pixel = height_m * (self.fov_rad / 2.0).tan() / self.resolution;
+        let dx_m = x * per_pixel;
+        let dy_m = y * per_pixel;
+        self.total_x_m += dx_m;
+        self.total_y_m += dy_m;
+        (dx_m, dy_m)
+    }
+
+    /// The running total displacement since construction or the last
+    /// [`FlowIntegrator::reset`], in meters.
+    pub fn total(&self) -> (f32, f32) {
+        (self.total_x_m, self.total_y_m)
+    }
+
+    /// Zeroes the running total, keeping the configured orientation/optics.
+    pub fn reset(&mut self) {
+        self.total_x_m = 0.0;
+        self.total_y_m = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlowIntegrator, Orientation};
+    use crate::{MotionBurst, Pmw3901Sample};
+
+    #[test]
+    fn orientation_maps_axes() {
+        assert_eq!(Orientation::Normal.apply(3.0, 5.0), (3.0, 5.0));
+        assert_eq!(Orientation::Rotate90.apply(3.0, 5.0), (-5.0, 3.0));
+        assert_eq!(Orientation::Rotate180.apply(3.0, 5.0), (-3.0, -5.0));
+        assert_eq!(Orientation::Rotate270.apply(3.0, 5.0), (5.0, -3.0));
+    }
+
+    // resolution = 10, fov = pi/2 so tan(fov/2) = 1, making per-pixel scale
+    // at 1m height a round 0.1m/pixel.
+    fn integrator(orientation: Orientation) -> FlowIntegrator {
+        FlowIntegrator::with_optics(orientation, 10.0, std::f32::consts::FRAC_PI_2)
+    }
+
+    #[test]
+    fn update_scales_by_height_and_fov() {
+        let mut flow = integrator(Orientation::Normal);
+        let sample = Pmw3901Sample { x: 5, y: -10 };
+        let (dx_m, dy_m) = flow.update(&sample, 2.0);
+        assert!((dx_m - 1.0).abs() < 1e-5);
+        assert!((dy_m - -2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn update_applies_orientation_before_scaling() {
+        let mut flow = integrator(Orientation::Rotate90);
+        let sample = Pmw3901Sample { x: 5, y: 0 };
+        let (dx_m, dy_m) = flow.update(&sample, 1.0);
+        assert!((dx_m - 0.0).abs() < 1e-5);
+        assert!((dy_m - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn update_from_burst_matches_update() {
+        let mut from_sample = integrator(Orientation::Normal);
+        let mut from_burst = integrator(Orientation::Normal);
+        let sample = Pmw3901Sample { x: 5, y: -10 };
+        let burst = MotionBurst {
+            motion: 0x80,
+            observation: 0,
+            dx: 5,
+            dy: -10,
+            squal: 0,
+            raw_data_sum: 0,
+            max_raw_data: 0,
+            min_raw_data: 0,
+            shutter: 0,
+        };
+        assert_eq!(
+            from_sample.update(&sample, 2.0),
+            from_burst.update_from_burst(&burst, 2.0),
+        );
+    }
+
+    #[test]
+    fn total_accumulates_and_reset_zeroes() {
+        let mut flow = integrator(Orientation::Normal);
+        let sample = Pmw3901Sample { x: 5, y: 5 };
+        flow.update(&sample, 1.0);
+        flow.update(&sample, 1.0);
+        let (total_x, total_y) = flow.total();
+        assert!((total_x - 1.0).abs() < 1e-5);
+        assert!((total_y - 1.0).abs() < 1e-5);
+
+        flow.reset();
+        assert_eq!(flow.total(), (0.0, 0.0));
+    }
+}
@@ -1,14 +1,28 @@
 //! A library for the PMW3901 optical flow sensor.
+//!
+//! The driver is generic over [`embedded_hal::spi::SpiDevice`] and
+//! [`embedded_hal::delay::DelayNs`], so the same `Pmw3901<SPI, D>` runs on a
+//! Linux SBC, a Zynq, or a bare-metal Cortex-M target - whatever HAL
+//! exposes those traits. Enable the `linux` feature for the original
+//! `/dev/spidev` entry point.
 
 extern crate byteorder;
-use byteorder::{ByteOrder, LittleEndian};
-extern crate spidev;
-use spidev::{Spidev, SpidevOptions, SpidevTransfer, SPI_MODE_3};
-use std::io;
-use std::thread;
-use std::time;
-
-/// Motion output of the sensor. 
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+extern crate embedded_hal;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::spi::{Operation, SpiDevice};
+
+mod config;
+pub use config::Config;
+mod error;
+pub use error::Pmw3901Error;
+mod flow;
+pub use flow::{FlowIntegrator, Orientation, DEFAULT_FOV_RAD, DEFAULT_RESOLUTION};
+
+#[cfg(feature = "linux")]
+pub mod linux;
+
+/// Motion output of the sensor.
 #[derive(Debug)]
 pub struct Pmw3901Sample {
     /// Unit is pixel velocity.
@@ -17,109 +31,151 @@ pub struct Pmw3901Sample {
     pub y: i16,
 }
 
+/// Full sensor status from a single Motion_Burst transaction.
+#[derive(Debug)]
+pub struct MotionBurst {
+    /// Raw motion flags register. Bit 7 set means new motion data is available.
+    pub motion: u8,
+    /// Raw observation register.
+    pub observation: u8,
+    /// X motion delta, in the same units as [`Pmw3901Sample::x`].
+    pub dx: i16,
+    /// Y motion delta, in the same units as [`Pmw3901Sample::y`].
+    pub dy: i16,
+    /// Surface quality. Low values mean the surface doesn't have enough
+    /// texture for reliable tracking.
+    pub squal: u8,
+    /// Sum of the raw pixel data, used to gauge exposure.
+    pub raw_data_sum: u8,
+    /// Maximum raw pixel value, used to detect over-exposure.
+    pub max_raw_data: u8,
+    /// Minimum raw pixel value, used to detect under-exposure.
+    pub min_raw_data: u8,
+    /// Shutter value. A shutter pinned at its limit means the sensor is
+    /// over- or under-exposed.
+    pub shutter: u16,
+}
+
+impl MotionBurst {
+    /// Whether the sensor reports new motion data since the last burst read.
+    pub fn motion_detected(&self) -> bool {
+        self.motion & 0x80 != 0
+    }
+}
+
 /// Optical flow sensor.
-pub struct Pmw3901 {
-    spi_dev: Spidev,
+pub struct Pmw3901<SPI, D> {
+    spi: SPI,
+    delay: D,
+    config: Config,
     pub debug: bool,
 }
 
-impl Pmw3901 {
-
-    // Initializes the SPI connection but does not use it.
-    pub fn new(bus: u8, chip_select: u8) -> io::Result<Pmw3901> {
-        let mut spi_dev = Spidev::open(
-            format!("/dev/spidev{}.{}", bus, chip_select))?;
-        let options = SpidevOptions::new()
-             .bits_per_word(8)
-             .max_speed_hz(2_000_000)
-             .lsb_first(false)
-             .mode(SPI_MODE_3)
-             .build();
-        spi_dev.configure(&options)?;
-        Ok(Pmw3901 {
-            spi_dev,
+impl<SPI, D, E> Pmw3901<SPI, D>
+where
+    SPI: SpiDevice<Error = E>,
+    D: DelayNs,
+{
+    /// Wraps an already-configured SPI device and a delay provider, using
+    /// [`Config::default`]. The caller is still responsible for the bus
+    /// speed/mode matching `config.max_speed_hz` (see the `linux` feature
+    /// for a reference setup).
+    pub fn new(spi: SPI, delay: D) -> Pmw3901<SPI, D> {
+        Pmw3901::with_config(spi, delay, Config::default())
+    }
+
+    /// Wraps an already-configured SPI device and delay provider with a
+    /// custom [`Config`].
+    pub fn with_config(spi: SPI, delay: D, config: Config) -> Pmw3901<SPI, D> {
+        Pmw3901 {
+            spi,
+            delay,
+            config,
             debug: false,
-        })
+        }
+    }
+
+    /// The [`Config`] this driver was constructed with.
+    pub fn config(&self) -> &Config {
+        &self.config
     }
 
     /// Read a value from a register.
-    pub fn read_register(&mut self, addr: u8) -> io::Result<u8> {
+    pub fn read_register(&mut self, addr: u8) -> Result<u8, Pmw3901Error<E>> {
         let tx_buf = [addr, 0];
         let mut rx_buf = [0; 2];
-        {
-            let mut transfer = SpidevTransfer::read_write(&tx_buf, &mut rx_buf);
-            self.spi_dev.transfer(&mut transfer)?;
-        }
+        self.spi.transfer(&mut rx_buf, &tx_buf)?;
         if rx_buf[0] != 0xff {
-            panic!("Unexpected first byte in read response: {}", rx_buf[0]);
+            return Err(Pmw3901Error::UnexpectedResponse { addr, got: rx_buf[0] });
         }
         Ok(rx_buf[1])
     }
 
     /// Write a value to a register.
-    pub fn write_register(&mut self, addr: u8, val: u8) -> io::Result<u8> {
+    pub fn write_register(&mut self, addr: u8, val: u8) -> Result<u8, Pmw3901Error<E>> {
         if addr & 0x80 > 0 {
-            panic!("Write bit already set on addr: {}", addr);
+            return Err(Pmw3901Error::WriteBitSet);
         }
         let tx_buf = [addr | 0x80, val];
         let mut rx_buf = [0; 2];
-        {
-            let mut transfer = SpidevTransfer::read_write(&tx_buf, &mut rx_buf);
-            self.spi_dev.transfer(&mut transfer)?;
-        }
+        self.spi.transfer(&mut rx_buf, &tx_buf)?;
         if rx_buf[0] != 0xff {
-            panic!("Unexpected first byte in read response: {}", rx_buf[0]);
+            return Err(Pmw3901Error::UnexpectedResponse { addr, got: rx_buf[0] });
         }
         if rx_buf[1] != 0xff {
-            panic!("Unexpected second byte in read response: {}", rx_buf[0]);
+            return Err(Pmw3901Error::UnexpectedResponse { addr, got: rx_buf[1] });
         }
         Ok(rx_buf[1])
     }
 
-    /// Helper for batch reading from multiple registers.
-    pub fn read_registers(&mut self, addrs: &[u8]) -> io::Result<Vec<u8>> {
-        let mut bufs = Vec::new();
-        for addr in addrs {
-            bufs.push(([*addr, 0], [0, 0]));
-        } 
+    /// Helper for batch reading from multiple registers. Like the original
+    /// `spidev::transfer_multiple`-based implementation, this is a single
+    /// SPI transaction with CS held low across the whole batch, not N
+    /// independent per-register transactions.
+    pub fn read_registers(&mut self, addrs: &[u8]) -> Result<Vec<u8>, Pmw3901Error<E>> {
+        let mut bufs: Vec<([u8; 2], [u8; 2])> =
+            addrs.iter().map(|&addr| ([addr, 0], [0, 0])).collect();
         {
-            let mut transfers = Vec::new();
-            for buf in bufs.iter_mut() {
-                let transfer = SpidevTransfer::read_write(&buf.0, &mut buf.1);
-                transfers.push(transfer);
-            }
-            self.spi_dev.transfer_multiple(&mut transfers)?;
+            let mut ops: Vec<Operation<u8>> = bufs
+                .iter_mut()
+                .map(|(tx, rx)| Operation::Transfer(rx, tx))
+                .collect();
+            self.spi.transaction(&mut ops)?;
         }
-        let mut res = Vec::new();
-        for buf in bufs {
-            if buf.1[0] != 0xff {
-                panic!("Unexpected first byte in read response: {}", buf.1[0]);
+        let mut res = Vec::with_capacity(addrs.len());
+        for (addr, (_, rx)) in addrs.iter().zip(bufs.iter()) {
+            if rx[0] != 0xff {
+                return Err(Pmw3901Error::UnexpectedResponse { addr: *addr, got: rx[0] });
             }
-            res.push(buf.1[1]);
+            res.push(rx[1]);
         }
         Ok(res)
     }
 
-    /// Helper for batch writing to multiple registers.
-    pub fn write_registers(&mut self, addrs_and_values: &[(u8, u8)]) -> io::Result<()> {
-        let mut bufs = Vec::new();
-        for &(addr, val) in addrs_and_values {
-            bufs.push(([addr | 0x80, val], [0, 0]));
-        } 
+    /// Helper for batch writing to multiple registers. Like the original
+    /// `spidev::transfer_multiple`-based implementation, this is a single
+    /// SPI transaction with CS held low across the whole batch, not N
+    /// independent per-register transactions - `write_init_registers` relies
+    /// on this to burst each of its two register batches (59 and 14 writes,
+    /// with a 100 ms delay between them) as one transaction.
+    pub fn write_registers(&mut self, addrs_and_values: &[(u8, u8)]) -> Result<(), Pmw3901Error<E>> {
+        let mut bufs: Vec<([u8; 2], [u8; 2])> = addrs_and_values
+            .iter()
+            .map(|&(addr, val)| ([addr | 0x80, val], [0, 0]))
+            .collect();
         {
-            let mut transfers = Vec::new();
-            for buf in bufs.iter_mut() {
-                let transfer = SpidevTransfer::read_write(&buf.0, &mut buf.1);
-                transfers.push(transfer);
-            }
-            self.spi_dev.transfer_multiple(&mut transfers)?;
+            let mut ops: Vec<Operation<u8>> = bufs
+                .iter_mut()
+                .map(|(tx, rx)| Operation::Transfer(rx, tx))
+                .collect();
+            self.spi.transaction(&mut ops)?;
         }
-        for buf in bufs {
-            if buf.1[0] != 0xff {
-                panic!("Unexpected first byte in write response: {}", buf.1[0]);
+        for (&(addr, _), (_, rx)) in addrs_and_values.iter().zip(bufs.iter()) {
+            if rx[0] != 0xff {
+                return Err(Pmw3901Error::UnexpectedResponse { addr, got: rx[0] });
             }
-            if buf.1[0] != 0xff {
-                panic!("Unexpected second byte in write response: {}", buf.1[0]);
+            if rx[1] != 0xff {
+                return Err(Pmw3901Error::UnexpectedResponse { addr, got: rx[1] });
             }
         }
         Ok(())
@@ -128,14 +184,14 @@ impl Pmw3901 {
     /// Initializes the device.
     /// * Validates known registers (Product ID).
     /// * Initializes device configuration.
-    pub fn init(&mut self) -> io::Result<()> {
+    pub fn init(&mut self) -> Result<(), Pmw3901Error<E>> {
         // Power on reset
         self.write_register(0x3a, 0x5a)?;
 
         // Verify product id
         let product_id = self.read_register(0x00)?;
         if product_id != 0x49 {
-            panic!("Unexpected product id: {} (expected 0x49)", product_id);
+            return Err(Pmw3901Error::BadProductId { got: product_id });
         }
         if self.debug {
             println!("Product ID: {:?}", product_id);
@@ -144,7 +200,7 @@ impl Pmw3901 {
         // Verify inverse product id
         let inverse_product_id = self.read_register(0x5f)?;
         if inverse_product_id != 0xb6 {
-            panic!("Unexpected inverse product id: {} (expected 0xb6)", product_id);
+            return Err(Pmw3901Error::BadProductId { got: inverse_product_id });
         }
         if self.debug {
             println!("Inverse Product ID: {:?}", inverse_product_id);
@@ -156,7 +212,7 @@ impl Pmw3901 {
     }
 
     /// This is black magic taken from the BitCraze source.
-    fn write_init_registers(&mut self) -> io::Result<()> {
+    fn write_init_registers(&mut self) -> Result<(), Pmw3901Error<E>> {
         self.write_registers(&[
             (0x7F, 0x00),
             (0x61, 0xAD),
@@ -219,7 +275,7 @@ impl Pmw3901 {
             (0x70, 0x00),
         ])?;
 
-        thread::sleep(time::Duration::from_millis(100));
+        self.delay.delay_ms(100);
 
         self.write_registers(&[
             (0x32, 0x44),
@@ -242,23 +298,95 @@ impl Pmw3901 {
     }
 
     /// Reads the x/y delta registers.
-    pub fn read_sample(&mut self) -> io::Result<Pmw3901Sample> {
-        //self.read_register(0x02)?;
-        //Ok(Pmw3901Sample {
-        //    x: LittleEndian::read_i16(&[self.read_register(0x03)?, self.read_register(0x04)?]),
-        //    y: LittleEndian::read_i16(&[self.read_register(0x05)?, self.read_register(0x06)?]),
-        //})
+    pub fn read_sample(&mut self) -> Result<Pmw3901Sample, Pmw3901Error<E>> {
         let res = self.read_registers(&[0x02, 0x03, 0x04, 0x05, 0x06])?;
         Ok(Pmw3901Sample {
             x: LittleEndian::read_i16(&res[1 .. 3]),
             y: LittleEndian::read_i16(&res[3 .. 5]),
         })
     }
+
+    /// Reads the full sensor status from the Motion_Burst register (0x16) in
+    /// a single SPI transaction, CS held low for all 12 bytes. Unlike
+    /// [`Pmw3901::read_sample`], this also surfaces `squal` and `shutter` so
+    /// callers can gate fusion on surface quality or detect over/under
+    /// exposure.
+    pub fn read_motion_burst(&mut self) -> Result<MotionBurst, Pmw3901Error<E>> {
+        let mut buf = [0u8; 12];
+        self.spi.transaction(&mut [
+            Operation::Write(&[0x16]),
+            Operation::Read(&mut buf),
+        ])?;
+        Ok(MotionBurst {
+            motion: buf[0],
+            observation: buf[1],
+            dx: LittleEndian::read_i16(&buf[2 .. 4]),
+            dy: LittleEndian::read_i16(&buf[4 .. 6]),
+            squal: buf[6],
+            raw_data_sum: buf[7],
+            max_raw_data: buf[8],
+            min_raw_data: buf[9],
+            shutter: BigEndian::read_u16(&buf[10 .. 12]),
+        })
+    }
+
+    /// Streams the sensor's full 35x35 raw pixel array, useful for aiming
+    /// and focusing the lens during bring-up.
+    ///
+    /// Runs the datasheet's raw-data-grab entry sequence (bank-select writes
+    /// to 0x7F, plus enable writes to 0x41/0x4C/0x6A/0x55/0x57/0x40/0x4D/0x58)
+    /// to halt normal motion processing, then polls the Raw_Data_Grab status
+    /// register, which
+    /// yields 2 bits of the current pixel per poll, 4 polls per pixel, 1225
+    /// pixels in row-major order. Normal motion mode is always restored
+    /// afterwards by re-running the init sequence, even if the capture
+    /// itself fails partway through - otherwise a transient SPI glitch would
+    /// strand the sensor in raw-data-grab mode until a power cycle.
+    pub fn capture_frame(&mut self) -> Result<[[u8; 35]; 35], Pmw3901Error<E>> {
+        let captured = self.capture_frame_raw();
+        let restored = self.write_init_registers();
+        let frame = captured?;
+        restored?;
+        Ok(frame)
+    }
+
+    fn capture_frame_raw(&mut self) -> Result<[[u8; 35]; 35], Pmw3901Error<E>> {
+        self.write_registers(&[
+            (0x7F, 0x07),
+            (0x41, 0x1D),
+            (0x4C, 0x00),
+            (0x7F, 0x08),
+            (0x6A, 0x38),
+            (0x7F, 0x00),
+            (0x55, 0x04),
+            (0x57, 0x30),
+            (0x40, 0x80),
+            (0x4D, 0x11),
+        ])?;
+        self.delay.delay_ms(10);
+        self.write_register(0x7F, 0x00)?;
+        self.write_register(0x58, 0xFF)?;
+
+        let mut frame = [[0u8; 35]; 35];
+        for row in frame.iter_mut() {
+            for pixel in row.iter_mut() {
+                let mut value = 0u8;
+                // Each status poll delivers 2 more bits of the pixel.
+                for _ in 0..4 {
+                    let status = self.read_register(0x58)?;
+                    value = (value << 2) | (status & 0x03);
+                }
+                *pixel = value;
+            }
+        }
+
+        Ok(frame)
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "linux"))]
 mod tests {
-    use super::{Pmw3901};
+    use super::linux;
     use std::env;
 
     fn get_spi_bus() -> u8 {
@@ -287,7 +415,7 @@ mod tests {
 
     #[test]
     fn basic() {
-        let mut pmw3901 = Pmw3901::new(
+        let mut pmw3901 = linux::open(
             get_spi_bus(), get_spi_cs()).unwrap();
         pmw3901.init().unwrap();
         pmw3901.read_sample().unwrap();
@@ -0,0 +1,36 @@
+//! Linux `/dev/spidev` entry point, preserved for existing users of the crate.
+
+use std::io;
+
+extern crate linux_embedded_hal;
+use linux_embedded_hal::{Delay, SpidevDevice};
+extern crate spidev;
+use spidev::{SpiModeFlags, SpidevOptions};
+
+use crate::{Config, Pmw3901};
+
+/// Opens `/dev/spidev{bus}.{chip_select}`, configures it the way the sensor
+/// expects using [`Config::default`], and wraps it for use with [`Pmw3901`].
+pub fn open(bus: u8, chip_select: u8) -> io::Result<Pmw3901<SpidevDevice, Delay>> {
+    open_with_config(bus, chip_select, Config::default())
+}
+
+/// Like [`open`], but lets the caller raise or lower the SPI clock (e.g. for
+/// margin on noisy wiring) via `config.max_speed_hz`.
+pub fn open_with_config(
+    bus: u8,
+    chip_select: u8,
+    config: Config,
+) -> io::Result<Pmw3901<SpidevDevice, Delay>> {
+    let mut spi_dev = SpidevDevice::open(
+        format!("/dev/spidev{}.{}", bus, chip_select))
+        .map_err(io::Error::other)?;
+    let options = SpidevOptions::new()
+        .bits_per_word(8)
+        .max_speed_hz(config.max_speed_hz)
+        .lsb_first(false)
+        .mode(SpiModeFlags::SPI_MODE_3)
+        .build();
+    spi_dev.0.configure(&options)?;
+    Ok(Pmw3901::with_config(spi_dev, Delay, config))
+}